@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::ini::{self, KeyFile};
+use crate::BaseDirectories;
+
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// IconThemeLookup allows to look up icon files according to
+/// the [Icon Theme specification][icon-theme].
+///
+/// [icon-theme]: https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
+///
+/// Icons are searched in `$HOME/.icons`, and then in `icons` in `XDG_DATA_HOME`
+/// and `XDG_DATA_DIRS` (without the prefix). Unthemed icons are also searched
+/// in `/usr/share/pixmaps`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xdg::IconThemeLookup;
+///
+/// let xdg_dirs = xdg::BaseDirectories::new();
+/// let icon_path = xdg_dirs.find_icon("firefox", 48, 1, "Adwaita");
+/// ```
+pub trait IconThemeLookup {
+    /// Given an icon name `name` (without an extension), returns an absolute
+    /// path to the icon file that best matches `size` at `scale` in `theme`,
+    /// or `None`. The themes `theme` inherits from are searched next,
+    /// then the `hicolor` theme, and finally unthemed icons.
+    fn find_icon(&self, name: &str, size: u16, scale: u16, theme: &str) -> Option<PathBuf>;
+}
+
+impl IconThemeLookup for BaseDirectories {
+    fn find_icon(&self, name: &str, size: u16, scale: u16, theme: &str) -> Option<PathBuf> {
+        let mut base_dirs = Vec::new();
+        if let Some(home) = self.home_dir() {
+            base_dirs.push(home.join(".icons"));
+        }
+        if let Some(ref data_home) = self.data_home {
+            base_dirs.push(data_home.join("icons"));
+        }
+        for dir in &self.data_dirs {
+            base_dirs.push(dir.join("icons"));
+        }
+
+        let lookup = IconLookup {
+            base_dirs: &base_dirs,
+            name,
+            size: u32::from(size),
+            scale: u32::from(scale),
+        };
+        let mut visited = HashSet::new();
+        lookup
+            .find_in_theme(theme, &mut visited)
+            .or_else(|| lookup.find_in_theme("hicolor", &mut visited))
+            .or_else(|| lookup.find_unthemed())
+    }
+}
+
+struct IconLookup<'a> {
+    base_dirs: &'a [PathBuf],
+    name: &'a str,
+    size: u32,
+    scale: u32,
+}
+
+impl<'a> IconLookup<'a> {
+    fn find_in_theme(&self, theme: &str, visited: &mut HashSet<String>) -> Option<PathBuf> {
+        // `Inherits` may form a cycle in a broken installation.
+        if !visited.insert(theme.to_owned()) {
+            return None;
+        }
+        let theme = Theme::load(self.base_dirs, theme)?;
+        if let Some(path) = self.find_in_subdirs(&theme) {
+            return Some(path);
+        }
+        theme
+            .parents
+            .iter()
+            .find_map(|parent| self.find_in_theme(parent, visited))
+    }
+
+    fn find_in_subdirs(&self, theme: &Theme) -> Option<PathBuf> {
+        for subdir in theme.subdirs.iter() {
+            if subdir.matches_size(self.size, self.scale) {
+                if let Some(path) = self.find_file(&theme.name, &subdir.path) {
+                    return Some(path);
+                }
+            }
+        }
+        let mut closest: Option<(u32, PathBuf)> = None;
+        for subdir in theme.subdirs.iter() {
+            let distance = subdir.size_distance(self.size, self.scale);
+            if closest.as_ref().map_or(true, |&(min, _)| distance < min) {
+                if let Some(path) = self.find_file(&theme.name, &subdir.path) {
+                    closest = Some((distance, path));
+                }
+            }
+        }
+        closest.map(|(_, path)| path)
+    }
+
+    fn find_file(&self, theme: &str, subdir: &str) -> Option<PathBuf> {
+        self.base_dirs
+            .iter()
+            .find_map(|dir| find_with_extension(&dir.join(theme).join(subdir), self.name))
+    }
+
+    fn find_unthemed(&self) -> Option<PathBuf> {
+        self.base_dirs
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(Some(Path::new("/usr/share/pixmaps")))
+            .find_map(|dir| find_with_extension(dir, self.name))
+    }
+}
+
+fn find_with_extension(dir: &Path, name: &str) -> Option<PathBuf> {
+    ICON_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+struct Theme {
+    name: String,
+    parents: Vec<String>,
+    subdirs: Vec<ThemeSubdir>,
+}
+
+impl Theme {
+    fn load(base_dirs: &[PathBuf], name: &str) -> Option<Theme> {
+        let index = base_dirs
+            .iter()
+            .map(|dir| dir.join(name).join("index.theme"))
+            .find(|path| path.is_file())?;
+        let index = KeyFile::load(&index).ok()?;
+
+        let mut subdirs = Vec::new();
+        for key in &["Directories", "ScaledDirectories"] {
+            let value = index.get("Icon Theme", key).unwrap_or("");
+            for subdir in ini::split_list(value, ',') {
+                if let Some(subdir) = ThemeSubdir::load(&index, subdir) {
+                    subdirs.push(subdir);
+                }
+            }
+        }
+        let parents = ini::split_list(index.get("Icon Theme", "Inherits").unwrap_or(""), ',')
+            .map(str::to_owned)
+            .collect();
+        Some(Theme {
+            name: name.to_owned(),
+            parents,
+            subdirs,
+        })
+    }
+}
+
+enum SubdirType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+struct ThemeSubdir {
+    path: String,
+    kind: SubdirType,
+    size: u32,
+    scale: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+impl ThemeSubdir {
+    fn load(index: &KeyFile, path: &str) -> Option<ThemeSubdir> {
+        let number = |key| {
+            index
+                .get(path, key)
+                .and_then(|value| value.parse::<u32>().ok())
+        };
+        let size = number("Size")?;
+        let kind = match index.get(path, "Type") {
+            Some("Fixed") => SubdirType::Fixed,
+            Some("Scalable") => SubdirType::Scalable,
+            _ => SubdirType::Threshold,
+        };
+        Some(ThemeSubdir {
+            path: path.to_owned(),
+            kind,
+            size,
+            scale: number("Scale").unwrap_or(1),
+            min_size: number("MinSize").unwrap_or(size),
+            max_size: number("MaxSize").unwrap_or(size),
+            threshold: number("Threshold").unwrap_or(2),
+        })
+    }
+
+    fn size_range(&self) -> (u32, u32) {
+        match self.kind {
+            SubdirType::Fixed => (self.size, self.size),
+            SubdirType::Scalable => (self.min_size, self.max_size),
+            SubdirType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size.saturating_add(self.threshold),
+            ),
+        }
+    }
+
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        let (min, max) = self.size_range();
+        self.scale == scale && min <= size && size <= max
+    }
+
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let (min, max) = self.size_range();
+        let scaled = size * scale;
+        // At most one of these is non-zero.
+        min.saturating_mul(self.scale).saturating_sub(scaled)
+            + scaled.saturating_sub(max.saturating_mul(self.scale))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::env;
+
+    fn icon_dirs() -> BaseDirectories {
+        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
+        let mut vars = BTreeMap::new();
+        vars.insert(
+            "XDG_DATA_HOME".to_string(),
+            format!("{}/test_files/icons/user", cwd),
+        );
+        vars.insert(
+            "XDG_DATA_DIRS".to_string(),
+            format!("{}/test_files/icons/system", cwd),
+        );
+        let home = PathBuf::from(format!("{}/test_files/icons/home", cwd));
+        BaseDirectories::from_env_map("", "", Some(&home), &vars)
+    }
+
+    fn icon_path(path: &str) -> Option<PathBuf> {
+        Some(
+            env::current_dir()
+                .unwrap()
+                .join("test_files/icons")
+                .join(path),
+        )
+    }
+
+    #[test]
+    fn test_find_icon() {
+        let xd = icon_dirs();
+        assert_eq!(
+            xd.find_icon("app", 48, 1, "mytheme"),
+            icon_path("user/icons/mytheme/48x48/apps/app.png")
+        );
+        assert_eq!(
+            xd.find_icon("app", 20, 1, "mytheme"),
+            icon_path("user/icons/mytheme/16x16/apps/app.png")
+        );
+        assert_eq!(
+            xd.find_icon("app", 24, 2, "mytheme"),
+            icon_path("user/icons/mytheme/48x48/apps/app.png")
+        );
+        assert_eq!(
+            xd.find_icon("vector", 256, 1, "mytheme"),
+            icon_path("user/icons/mytheme/scalable/apps/vector.svg")
+        );
+    }
+
+    #[test]
+    fn test_find_icon_fallbacks() {
+        let xd = icon_dirs();
+        assert_eq!(
+            xd.find_icon("inherited", 16, 1, "mytheme"),
+            icon_path("system/icons/parenttheme/32x32/apps/inherited.png")
+        );
+        assert_eq!(
+            xd.find_icon("hicolor-only", 16, 1, "mytheme"),
+            icon_path("system/icons/hicolor/22x22/apps/hicolor-only.png")
+        );
+        assert_eq!(
+            xd.find_icon("unthemed", 16, 1, "mytheme"),
+            icon_path("system/icons/unthemed.xpm")
+        );
+        assert_eq!(xd.find_icon("app", 16, 1, "nonexistent"), None);
+        assert_eq!(xd.find_icon("missing", 16, 1, "mytheme"), None);
+    }
+
+    #[test]
+    fn test_find_icon_home() {
+        let xd = icon_dirs();
+        assert_eq!(
+            xd.find_icon("home-only", 16, 1, "mytheme"),
+            icon_path("home/.icons/mytheme/16x16/apps/home-only.png")
+        );
+    }
+
+    #[test]
+    fn test_find_icon_out_of_range() {
+        let xd = icon_dirs();
+        assert_eq!(
+            xd.find_icon("other", 16, 2, "broken"),
+            icon_path("system/icons/broken/16x16/apps/other.png")
+        );
+    }
+}
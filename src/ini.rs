@@ -0,0 +1,68 @@
+use std::path::Path;
+use std::{fs, io};
+
+/// A minimal parser for the key file format used by `index.theme`
+/// and `mimeapps.list`: `[Group]` headers followed by `Key=Value` lines,
+/// with `#` comments.
+///
+/// Groups and keys are kept in file order; lookups return the first match.
+#[derive(Debug, Default)]
+pub(crate) struct KeyFile {
+    groups: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl KeyFile {
+    pub(crate) fn parse(contents: &str) -> KeyFile {
+        let mut groups: Vec<(String, Vec<(String, String)>)> = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                groups.push((line[1..line.len() - 1].to_owned(), Vec::new()));
+            } else if let (Some((key, value)), Some(group)) = (split_key(line), groups.last_mut()) {
+                group.1.push((key.to_owned(), value.to_owned()));
+            }
+        }
+        KeyFile { groups }
+    }
+
+    pub(crate) fn load(path: &Path) -> io::Result<KeyFile> {
+        Ok(KeyFile::parse(&fs::read_to_string(path)?))
+    }
+
+    pub(crate) fn entries<'a, 'b>(
+        &'a self,
+        group: &'b str,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'b
+    where
+        'a: 'b,
+    {
+        self.groups
+            .iter()
+            .filter(move |(name, _)| name == group)
+            .flat_map(|(_, entries)| entries.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub(crate) fn get(&self, group: &str, key: &str) -> Option<&str> {
+        self.entries(group)
+            .find(|&(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+}
+
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    let index = line.find('=')?;
+    Some((line[..index].trim_end(), line[index + 1..].trim_start()))
+}
+
+/// Splits a list value on `separator`, ignoring empty items
+/// (such as the one after a trailing separator).
+pub(crate) fn split_list(value: &str, separator: char) -> impl Iterator<Item = &str> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
@@ -2,5 +2,21 @@
 
 mod base_directories;
 pub use crate::base_directories::{
-    BaseDirectories, Error as BaseDirectoriesError, FileFindIterator,
+    BaseDirectories, BaseDirectoryPaths, Category, ConfigSource, Error as BaseDirectoriesError,
+    ErrorKind as BaseDirectoriesErrorKind, FileFindIterator, FileListIterator, RuntimeDirStatus,
+    RuntimeFileGuard,
 };
+
+mod icon_theme;
+pub use crate::icon_theme::IconThemeLookup;
+
+mod mime_apps;
+pub use crate::mime_apps::{current_desktops, MimeApps};
+
+mod shared_mime_info;
+pub use crate::shared_mime_info::SharedMimeInfo;
+
+mod trash;
+pub use crate::trash::Trash;
+
+mod ini;
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::env;
+
+use crate::ini::{self, KeyFile};
+use crate::BaseDirectories;
+
+const DEFAULT_APPLICATIONS: &str = "Default Applications";
+const ADDED_ASSOCIATIONS: &str = "Added Associations";
+const REMOVED_ASSOCIATIONS: &str = "Removed Associations";
+
+/// MimeApps allows to look up the applications associated with a MIME type
+/// according to the [Association between MIME types and applications
+/// specification][mime-apps].
+///
+/// [mime-apps]: https://specifications.freedesktop.org/mime-apps-spec/mime-apps-spec-latest.html
+///
+/// The `mimeapps.list` files are read from `XDG_CONFIG_HOME`, `XDG_CONFIG_DIRS`,
+/// and then from `applications` in `XDG_DATA_HOME` and `XDG_DATA_DIRS`, which
+/// the specification deprecates but still lists (all without the prefix);
+/// files found earlier take precedence.
+/// In each directory, the desktop-specific `$desktop-mimeapps.list` files for
+/// the [current desktops](current_desktops) take precedence over `mimeapps.list`.
+/// Applications are identified by their desktop file ID, e.g. `org.gnome.gedit.desktop`;
+/// whether the application is actually installed is not checked.
+///
+/// # Examples
+///
+/// ```no_run
+/// let xdg_dirs = xdg::BaseDirectories::new();
+/// let mime_apps = xdg::MimeApps::new(&xdg_dirs);
+/// let text_editor = mime_apps.default_application("text/plain");
+/// ```
+#[derive(Debug)]
+pub struct MimeApps {
+    files: Vec<KeyFile>,
+}
+
+impl MimeApps {
+    /// Reads every `mimeapps.list` file found for `dirs`.
+    /// Files that cannot be read are skipped.
+    pub fn new(dirs: &BaseDirectories) -> MimeApps {
+        MimeApps::with_desktops(dirs, &current_desktops())
+    }
+
+    fn with_desktops(dirs: &BaseDirectories, desktops: &[String]) -> MimeApps {
+        let search_dirs = dirs
+            .config_home
+            .iter()
+            .chain(dirs.config_dirs.iter())
+            .cloned()
+            .chain(
+                dirs.data_home
+                    .iter()
+                    .chain(dirs.data_dirs.iter())
+                    .map(|dir| dir.join("applications")),
+            );
+        let mut files = Vec::new();
+        for dir in search_dirs {
+            let names = desktops
+                .iter()
+                .map(|desktop| format!("{}-mimeapps.list", desktop.to_lowercase()))
+                .chain(Some("mimeapps.list".to_owned()));
+            for name in names {
+                if let Ok(file) = KeyFile::load(&dir.join(name)) {
+                    files.push(file);
+                }
+            }
+        }
+        MimeApps { files }
+    }
+
+    /// Returns the desktop file ID of the default application for `mime_type`,
+    /// or `None`. If no default application is set, the most preferred
+    /// associated application is returned instead.
+    pub fn default_application(&self, mime_type: &str) -> Option<String> {
+        self.files
+            .iter()
+            .find_map(|file| ids(file, DEFAULT_APPLICATIONS, mime_type).next())
+            .map(str::to_owned)
+            .or_else(|| self.associations(mime_type).into_iter().next())
+    }
+
+    /// Returns the desktop file IDs of all applications associated with `mime_type`,
+    /// ordered by preference, starting with the default application.
+    pub fn all_applications(&self, mime_type: &str) -> Vec<String> {
+        let mut applications = Vec::new();
+        if let Some(default) = self
+            .files
+            .iter()
+            .find_map(|file| ids(file, DEFAULT_APPLICATIONS, mime_type).next())
+        {
+            applications.push(default.to_owned());
+        }
+        for id in self.associations(mime_type) {
+            if !applications.contains(&id) {
+                applications.push(id);
+            }
+        }
+        applications
+    }
+
+    fn associations(&self, mime_type: &str) -> Vec<String> {
+        let mut associations = Vec::new();
+        // Removed associations only apply to files with a lower precedence.
+        let mut removed = HashSet::new();
+        for file in &self.files {
+            for id in ids(file, ADDED_ASSOCIATIONS, mime_type) {
+                if !removed.contains(id) && !associations.iter().any(|other| other == id) {
+                    associations.push(id.to_owned());
+                }
+            }
+            removed.extend(ids(file, REMOVED_ASSOCIATIONS, mime_type));
+        }
+        associations
+    }
+}
+
+/// Returns the names of the current desktop environments, most important first,
+/// as listed in `XDG_CURRENT_DESKTOP`, e.g. `["ubuntu", "GNOME"]`.
+/// Returns an empty list if `XDG_CURRENT_DESKTOP` is not set.
+pub fn current_desktops() -> Vec<String> {
+    split_desktops(&env::var("XDG_CURRENT_DESKTOP").unwrap_or_default())
+}
+
+fn split_desktops(value: &str) -> Vec<String> {
+    ini::split_list(value, ':').map(str::to_owned).collect()
+}
+
+fn ids<'a>(file: &'a KeyFile, group: &str, mime_type: &str) -> impl Iterator<Item = &'a str> {
+    ini::split_list(file.get(group, mime_type).unwrap_or(""), ';')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::OsString;
+
+    fn mime_apps() -> MimeApps {
+        mime_apps_with_desktops(&[])
+    }
+
+    fn mime_apps_with_desktops(desktops: &[String]) -> MimeApps {
+        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
+        let dirs = BaseDirectories::with_env("", "", &|name| match name {
+            "XDG_CONFIG_HOME" => Some(OsString::from(format!("{}/test_files/mimeapps/user", cwd))),
+            "XDG_CONFIG_DIRS" => Some(OsString::from(format!(
+                "{}/test_files/mimeapps/system:{}/test_files/mimeapps/missing",
+                cwd, cwd
+            ))),
+            "XDG_DATA_HOME" => Some(OsString::from(format!(
+                "{}/test_files/mimeapps/missing",
+                cwd
+            ))),
+            "XDG_DATA_DIRS" => Some(OsString::from(format!("{}/test_files/mimeapps/data", cwd))),
+            _ => None,
+        });
+        MimeApps::with_desktops(&dirs, desktops)
+    }
+
+    #[test]
+    fn test_default_application() {
+        let mime_apps = mime_apps();
+        assert_eq!(
+            mime_apps.default_application("text/plain"),
+            Some("kate.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("image/png"),
+            Some("eog.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("text/html"),
+            Some("firefox.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("application/pdf"),
+            Some("evince.desktop".to_string())
+        );
+        assert_eq!(mime_apps.default_application("video/mp4"), None);
+    }
+
+    #[test]
+    fn test_all_applications() {
+        let mime_apps = mime_apps();
+        assert_eq!(
+            mime_apps.all_applications("text/plain"),
+            vec!["kate.desktop", "vim.desktop", "gedit.desktop"]
+        );
+        assert_eq!(
+            mime_apps.all_applications("image/png"),
+            vec!["eog.desktop", "krita.desktop"]
+        );
+        assert_eq!(
+            mime_apps.all_applications("application/pdf"),
+            vec!["evince.desktop", "okular.desktop"]
+        );
+        assert!(mime_apps.all_applications("video/mp4").is_empty());
+    }
+
+    #[test]
+    fn test_desktop_specific() {
+        let mime_apps = mime_apps_with_desktops(&split_desktops("KDE"));
+        assert_eq!(
+            mime_apps.default_application("text/html"),
+            Some("konqueror.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("text/plain"),
+            Some("kate.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("application/pdf"),
+            Some("okular.desktop".to_string())
+        );
+
+        let mime_apps = mime_apps_with_desktops(&split_desktops("ubuntu:GNOME"));
+        assert_eq!(
+            mime_apps.default_application("text/html"),
+            Some("firefox.desktop".to_string())
+        );
+        assert_eq!(
+            mime_apps.default_application("application/pdf"),
+            Some("evince.desktop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_desktops() {
+        assert_eq!(split_desktops("ubuntu:GNOME"), vec!["ubuntu", "GNOME"]);
+        assert_eq!(split_desktops("KDE:"), vec!["KDE"]);
+        assert!(split_desktops("").is_empty());
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::base_directories::glob_match;
+use crate::BaseDirectories;
+
+const MAGIC_HEADER: &[u8] = b"MIME-Magic\0\n";
+
+/// SharedMimeInfo allows to determine the MIME type of a file according to
+/// the [Shared MIME-info Database specification][shared-mime-info].
+///
+/// [shared-mime-info]: https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html
+///
+/// The `mime/globs2` and `mime/magic` files are read from `XDG_DATA_HOME`
+/// and then `XDG_DATA_DIRS` (without the prefix); files found earlier take
+/// precedence. Other files of the database, such as `mime/aliases` or
+/// `mime/subclasses`, are not used.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let xdg_dirs = xdg::BaseDirectories::new();
+/// let mime_info = xdg::SharedMimeInfo::new(&xdg_dirs);
+/// let mime_type = mime_info.guess_mime_type(Path::new("report.pdf"));
+/// ```
+#[derive(Debug)]
+pub struct SharedMimeInfo {
+    globs: Vec<Glob>,
+    magic: Vec<MagicSection>,
+}
+
+#[derive(Debug)]
+struct Glob {
+    weight: u32,
+    mime_type: String,
+    pattern: Vec<char>,
+    case_sensitive: bool,
+}
+
+#[derive(Debug)]
+struct MagicSection {
+    priority: u32,
+    mime_type: String,
+    rules: Vec<MagicRule>,
+}
+
+#[derive(Debug)]
+struct MagicRule {
+    indent: usize,
+    start: usize,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    range: usize,
+}
+
+impl SharedMimeInfo {
+    /// Reads the `mime/globs2` and `mime/magic` files found for `dirs`.
+    /// Files that cannot be read are skipped.
+    pub fn new(dirs: &BaseDirectories) -> SharedMimeInfo {
+        let dirs = BaseDirectories {
+            shared_prefix: PathBuf::new(),
+            user_prefix: PathBuf::new(),
+            ..dirs.clone()
+        };
+
+        // `__NOGLOBS__` discards the patterns of a MIME type that are defined
+        // in files with a lower precedence.
+        let mut globs = Vec::new();
+        let mut noglobs = HashSet::new();
+        for path in dirs.find_data_files("mime/globs2").rev() {
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            let mut file_noglobs = Vec::new();
+            for glob in contents.lines().filter_map(parse_glob) {
+                if noglobs.contains(&glob.mime_type) {
+                    continue;
+                }
+                if glob.pattern.iter().copied().eq("__NOGLOBS__".chars()) {
+                    file_noglobs.push(glob.mime_type);
+                } else {
+                    globs.push(glob);
+                }
+            }
+            noglobs.extend(file_noglobs);
+        }
+
+        let magic = dirs
+            .find_data_files("mime/magic")
+            .rev()
+            .filter_map(|path| fs::read(path).ok())
+            .filter_map(|contents| parse_magic(&contents))
+            .flatten()
+            .collect();
+        SharedMimeInfo { globs, magic }
+    }
+
+    /// Returns the MIME type of the file at `path`, or `None`. The file name
+    /// is matched against glob patterns first; if no pattern matches,
+    /// the contents of the file are matched against magic rules.
+    pub fn guess_mime_type(&self, path: &Path) -> Option<String> {
+        path.file_name()
+            .and_then(|name| self.mime_type_for_name(&name.to_string_lossy()))
+            .or_else(|| self.mime_type_for_contents(path))
+            .map(str::to_owned)
+    }
+
+    fn mime_type_for_name(&self, name: &str) -> Option<&str> {
+        let name_chars = name.chars().collect::<Vec<_>>();
+        let lowercase_chars = name.to_lowercase().chars().collect::<Vec<_>>();
+        // The highest weight wins, then the longest pattern, then the first one read.
+        let mut best: Option<&Glob> = None;
+        for glob in &self.globs {
+            let matched = if glob.case_sensitive {
+                glob_match(&glob.pattern, &name_chars)
+            } else {
+                glob_match(&glob.pattern, &lowercase_chars)
+            };
+            let better = best.map_or(true, |best| {
+                (glob.weight, glob.pattern.len()) > (best.weight, best.pattern.len())
+            });
+            if matched && better {
+                best = Some(glob);
+            }
+        }
+        best.map(|glob| glob.mime_type.as_str())
+    }
+
+    fn mime_type_for_contents(&self, path: &Path) -> Option<&str> {
+        let extent = self
+            .magic
+            .iter()
+            .flat_map(|section| section.rules.iter())
+            .map(|rule| {
+                rule.start
+                    .saturating_add(rule.range)
+                    .saturating_add(rule.value.len())
+            })
+            .max()?;
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|file| file.take(extent as u64).read_to_end(&mut data))
+            .ok()?;
+
+        let mut best: Option<&MagicSection> = None;
+        for section in &self.magic {
+            let better = best.map_or(true, |best| section.priority > best.priority);
+            if better && any_rule_matches(&section.rules, &data) {
+                best = Some(section);
+            }
+        }
+        best.map(|section| section.mime_type.as_str())
+    }
+}
+
+/// Parses a `weight:mime/type:pattern[:flags]` line.
+fn parse_glob(line: &str) -> Option<Glob> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let mut fields = line.splitn(4, ':');
+    let weight = fields.next()?.parse().ok()?;
+    let mime_type = fields.next()?.to_owned();
+    let pattern = fields.next()?;
+    let case_sensitive = fields
+        .next()
+        .map_or(false, |flags| flags.split(',').any(|flag| flag == "cs"));
+    // Case-insensitive patterns are matched against the lowercased file name.
+    let pattern = if case_sensitive || pattern == "__NOGLOBS__" {
+        pattern.chars().collect()
+    } else {
+        pattern.to_lowercase().chars().collect()
+    };
+    Some(Glob {
+        weight,
+        mime_type,
+        pattern,
+        case_sensitive,
+    })
+}
+
+/// Parses the binary `magic` file format; returns `None` if it is malformed.
+fn parse_magic(mut data: &[u8]) -> Option<Vec<MagicSection>> {
+    if !data.starts_with(MAGIC_HEADER) {
+        return None;
+    }
+    data = &data[MAGIC_HEADER.len()..];
+
+    let mut sections: Vec<MagicSection> = Vec::new();
+    while !data.is_empty() {
+        if data[0] == b'[' {
+            let end = data.iter().position(|&byte| byte == b'\n')?;
+            let header = std::str::from_utf8(&data[1..end]).ok()?.strip_suffix(']')?;
+            let (priority, mime_type) = header.split_once(':')?;
+            sections.push(MagicSection {
+                priority: priority.parse().ok()?,
+                mime_type: mime_type.to_owned(),
+                rules: Vec::new(),
+            });
+            data = &data[end + 1..];
+        } else {
+            let rule = parse_magic_rule(&mut data)?;
+            sections.last_mut()?.rules.push(rule);
+        }
+    }
+    Some(sections)
+}
+
+/// Parses a `[indent]>start=value[&mask][~word-size][+range]` line.
+fn parse_magic_rule(data: &mut &[u8]) -> Option<MagicRule> {
+    let indent = parse_number(data).unwrap_or(0);
+    expect_byte(data, b'>')?;
+    let start = parse_number(data)?;
+    expect_byte(data, b'=')?;
+    let length = usize::from(u16::from_be_bytes([*data.first()?, *data.get(1)?]));
+    let mut value = data.get(2..2 + length)?.to_vec();
+    *data = &data[2 + length..];
+
+    let mut mask = None;
+    let mut word_size = 1;
+    let mut range = 1;
+    loop {
+        match *data.first()? {
+            b'&' => {
+                mask = Some(data.get(1..1 + length)?.to_vec());
+                *data = &data[1 + length..];
+            }
+            b'~' => {
+                *data = &data[1..];
+                word_size = parse_number(data)?;
+            }
+            b'+' => {
+                *data = &data[1..];
+                range = parse_number(data)?;
+            }
+            b'\n' => {
+                *data = &data[1..];
+                break;
+            }
+            // Unknown extensions are ignored until the end of the line.
+            _ => {
+                let end = data.iter().position(|&byte| byte == b'\n')?;
+                *data = &data[end + 1..];
+                break;
+            }
+        }
+    }
+
+    // Values of multi-byte words are stored in big-endian byte order.
+    if cfg!(target_endian = "little") && word_size > 1 {
+        for word in value.chunks_mut(word_size) {
+            word.reverse();
+        }
+        if let Some(ref mut mask) = mask {
+            for word in mask.chunks_mut(word_size) {
+                word.reverse();
+            }
+        }
+    }
+    Some(MagicRule {
+        indent,
+        start,
+        value,
+        mask,
+        range: range.max(1),
+    })
+}
+
+fn parse_number(data: &mut &[u8]) -> Option<usize> {
+    let digits = data.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&data[..digits]).ok()?.parse().ok()?;
+    *data = &data[digits..];
+    Some(number)
+}
+
+fn expect_byte(data: &mut &[u8], byte: u8) -> Option<()> {
+    if data.first() == Some(&byte) {
+        *data = &data[1..];
+        Some(())
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if any rule at the indentation level of the first rule
+/// matches, together with at least one of its nested rules, if it has any.
+fn any_rule_matches(rules: &[MagicRule], data: &[u8]) -> bool {
+    let level = match rules.first() {
+        Some(rule) => rule.indent,
+        None => return false,
+    };
+    let mut index = 0;
+    while index < rules.len() {
+        let nested = rules[index + 1..]
+            .iter()
+            .take_while(|rule| rule.indent > level)
+            .count();
+        let children = &rules[index + 1..index + 1 + nested];
+        if rules[index].matches(data) && (children.is_empty() || any_rule_matches(children, data)) {
+            return true;
+        }
+        index += 1 + nested;
+    }
+    false
+}
+
+impl MagicRule {
+    fn matches(&self, data: &[u8]) -> bool {
+        // Offsets past the end of `data` can never match.
+        let end = self.start.saturating_add(self.range).min(data.len());
+        (self.start..end).any(|offset| {
+            let bytes = match data.get(offset..offset + self.value.len()) {
+                Some(bytes) => bytes,
+                None => return false,
+            };
+            match self.mask {
+                Some(ref mask) => bytes
+                    .iter()
+                    .zip(mask)
+                    .zip(&self.value)
+                    .all(|((byte, mask), value)| byte & mask == value & mask),
+                None => bytes == &self.value[..],
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::ffi::OsString;
+
+    fn mime_info() -> SharedMimeInfo {
+        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
+        SharedMimeInfo::new(&BaseDirectories::with_env("", "", &|name| match name {
+            "XDG_DATA_HOME" => Some(OsString::from(format!("{}/test_files/mime/user", cwd))),
+            "XDG_DATA_DIRS" => Some(OsString::from(format!(
+                "{}/test_files/mime/system:{}/test_files/mime/missing",
+                cwd, cwd
+            ))),
+            _ => None,
+        }))
+    }
+
+    fn guess(mime_info: &SharedMimeInfo, name: &str) -> Option<String> {
+        let path = env::current_dir()
+            .unwrap()
+            .join("test_files/mime/samples")
+            .join(name);
+        mime_info.guess_mime_type(&path)
+    }
+
+    #[test]
+    fn test_guess_by_name() {
+        let mime_info = mime_info();
+        assert_eq!(
+            guess(&mime_info, "data.foo"),
+            Some("application/x-foo".to_string())
+        );
+        assert_eq!(
+            guess(&mime_info, "DATA.FOO"),
+            Some("application/x-foo".to_string())
+        );
+        assert_eq!(
+            guess(&mime_info, "archive.tar.gz"),
+            Some("application/x-compressed-tar".to_string())
+        );
+        assert_eq!(
+            guess(&mime_info, "Makefile"),
+            Some("text/x-makefile".to_string())
+        );
+        // `__NOGLOBS__` in the user database hides the system patterns.
+        assert_eq!(guess(&mime_info, "notes.old"), None);
+        assert_eq!(
+            guess(&mime_info, "notes.new"),
+            Some("text/x-notes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_guess_by_contents() {
+        let mime_info = mime_info();
+        assert_eq!(
+            guess(&mime_info, "magic-file"),
+            Some("application/x-magic".to_string())
+        );
+        assert_eq!(
+            guess(&mime_info, "nested-file"),
+            Some("application/x-nested".to_string())
+        );
+        assert_eq!(
+            guess(&mime_info, "masked-file"),
+            Some("application/x-masked".to_string())
+        );
+        assert_eq!(guess(&mime_info, "unknown-file"), None);
+        assert_eq!(guess(&mime_info, "missing-file"), None);
+    }
+
+    #[test]
+    fn test_parse_magic() {
+        assert!(parse_magic(b"not magic").is_none());
+        let sections =
+            parse_magic(b"MIME-Magic\0\n[50:text/x-a]\n>4=\0\x02ab+8\n1>0=\0\x01c&\xff~2\n")
+                .unwrap();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].priority, 50);
+        assert_eq!(sections[0].mime_type, "text/x-a");
+        let rules = &sections[0].rules;
+        assert_eq!((rules[0].indent, rules[0].start, rules[0].range), (0, 4, 8));
+        assert_eq!(rules[0].value, b"ab");
+        assert_eq!((rules[1].indent, rules[1].start), (1, 0));
+        assert_eq!(rules[1].mask, Some(vec![0xff]));
+    }
+
+    #[test]
+    fn test_magic_rule_bounds() {
+        let rule = MagicRule {
+            indent: 0,
+            start: usize::MAX - 1,
+            value: b"ab".to_vec(),
+            mask: None,
+            range: usize::MAX,
+        };
+        assert!(!rule.matches(b"abc"));
+        let rule = MagicRule {
+            start: 1,
+            range: usize::MAX,
+            ..rule
+        };
+        assert!(rule.matches(b"xxab"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::BaseDirectories;
+
+/// Trash allows to move files to the trash according to
+/// the [Trash specification][trash].
+///
+/// [trash]: https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+///
+/// Files on the same filesystem as `XDG_DATA_HOME` are moved to the home trash,
+/// `$XDG_DATA_HOME/Trash`. Files on other filesystems are moved to the trash
+/// directory at the top of that filesystem, `$topdir/.Trash/$uid` or
+/// `$topdir/.Trash-$uid`.
+///
+/// Unlike what the specification asks for, the deletion date recorded
+/// in the `.trashinfo` file is in UTC rather than in local time, since
+/// determining the local time zone would require `libc`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::io::Error;
+/// # fn main() -> Result<(), Error> {
+/// let xdg_dirs = xdg::BaseDirectories::new();
+/// let trash = xdg::Trash::new(&xdg_dirs);
+/// trash.trash_file("unwanted.txt")?;
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Trash {
+    home_trash: Option<PathBuf>,
+}
+
+impl Trash {
+    /// Returns a value that can be used to move files to the trash
+    /// for the `XDG_DATA_HOME` of `dirs` (without the prefix).
+    pub fn new(dirs: &BaseDirectories) -> Trash {
+        Trash {
+            home_trash: dirs.data_home.as_ref().map(|home| home.join("Trash")),
+        }
+    }
+
+    /// Returns the home trash directory, `$XDG_DATA_HOME/Trash`.
+    /// Is guaranteed to not return `None` unless no HOME could be found.
+    pub fn home_trash(&self) -> Option<PathBuf> {
+        self.home_trash.clone()
+    }
+
+    /// Moves the file or directory at `path` to the trash, and returns
+    /// the path it was moved to. The `files` and `info` directories in the trash
+    /// are created if they did not exist. If a file with the same name is
+    /// already in the trash, a counter is appended to the name.
+    pub fn trash_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = env::current_dir()?.join(path);
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash `/`"))?
+            .to_owned();
+        // Resolve symlinks in the parent directories, so that the right
+        // mount point is found, but not in `path` itself, so that a symlink
+        // is trashed rather than its target.
+        let path = fs::canonicalize(path.parent().unwrap_or(&path))?.join(&name);
+        let device = fs::symlink_metadata(&path)?.dev();
+
+        let (trash, relative_to) = match self.home_trash {
+            Some(ref home_trash) if device_of(home_trash)? == device => (home_trash.clone(), None),
+            _ => {
+                let topdir = topdir(&path, device);
+                (topdir_trash(&topdir, self.uid()?)?, Some(topdir))
+            }
+        };
+        let files = trash.join("files");
+        let info = trash.join("info");
+        create_private_dir(&files)?;
+        create_private_dir(&info)?;
+
+        // Reserve a name by creating the info file exclusively, as the
+        // specification requires.
+        let mut counter = 1;
+        let (trashed_name, mut info_file, info_path) = loop {
+            let mut trashed_name = name.to_owned();
+            if counter > 1 {
+                trashed_name.push(format!(".{}", counter));
+            }
+            let mut info_name = trashed_name.clone();
+            info_name.push(".trashinfo");
+            let info_path = info.join(info_name);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                // A dangling symlink in `files` is still taken.
+                Ok(file) if fs::symlink_metadata(files.join(&trashed_name)).is_err() => {
+                    break (trashed_name, file, info_path)
+                }
+                Ok(_) => fs::remove_file(&info_path)?,
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+                Err(e) => return Err(e),
+            }
+            counter += 1;
+        };
+
+        let original_path = match relative_to {
+            Some(ref topdir) => path.strip_prefix(topdir).unwrap_or(&path),
+            None => &path,
+        };
+        let trashed_path = files.join(&trashed_name);
+        let result = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            escape_path(original_path.as_os_str()),
+            format_deletion_date(SystemTime::now())
+        )
+        .and_then(|()| fs::rename(&path, &trashed_path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        Ok(trashed_path)
+    }
+
+    // The home trash is owned by the current user, and using it avoids
+    // a dependency on `libc` just for `getuid()`.
+    fn uid(&self) -> io::Result<u32> {
+        let home_trash = self
+            .home_trash
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "$HOME must be set"))?;
+        create_private_dir(home_trash)?;
+        Ok(fs::metadata(home_trash)?.uid())
+    }
+}
+
+/// Returns the device of `path`, or of its closest existing ancestor.
+fn device_of(path: &Path) -> io::Result<u64> {
+    let mut error = None;
+    for ancestor in path.ancestors() {
+        match fs::metadata(ancestor) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+}
+
+/// Returns the mount point of the filesystem `path` is on.
+fn topdir(path: &Path, device: u64) -> PathBuf {
+    let mut topdir = path.parent().unwrap_or(path);
+    for ancestor in path.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(metadata) if metadata.dev() == device => topdir = ancestor,
+            _ => break,
+        }
+    }
+    topdir.to_path_buf()
+}
+
+fn topdir_trash(topdir: &Path, uid: u32) -> io::Result<PathBuf> {
+    // `$topdir/.Trash` must be a real directory with the sticky bit set.
+    let admin_trash = topdir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&admin_trash) {
+        if metadata.is_dir() && metadata.mode() & 0o1000 != 0 {
+            let user_trash = admin_trash.join(uid.to_string());
+            if create_private_dir(&user_trash).is_ok() {
+                return Ok(user_trash);
+            }
+        }
+    }
+    let user_trash = topdir.join(format!(".Trash-{}", uid));
+    create_private_dir(&user_trash)?;
+    Ok(user_trash)
+}
+
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)
+}
+
+/// Percent-encodes `path` as required for the `Path` key.
+fn escape_path(path: &OsStr) -> String {
+    let mut escaped = String::new();
+    for &byte in path.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+/// Formats `time` as `YYYY-MM-DDThh:mm:ss` in UTC.
+fn format_deletion_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Converts days since the epoch to a proleptic Gregorian date; see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::OsString;
+    use std::time::Duration;
+
+    #[test]
+    fn test_home_trash() {
+        let trash = Trash::new(&BaseDirectories::with_env("", "", &|name| match name {
+            "XDG_DATA_HOME" => Some(OsString::from("/home/user/data")),
+            _ => None,
+        }));
+        assert_eq!(
+            trash.home_trash(),
+            Some(PathBuf::from("/home/user/data/Trash"))
+        );
+    }
+
+    #[test]
+    fn test_trash_file() {
+        let test_dir = env::current_dir().unwrap().join("test_files/trash");
+        let data_home = test_dir.join("data");
+        let trash = Trash::new(&BaseDirectories::with_env("", "", &|name| match name {
+            "XDG_DATA_HOME" => Some(data_home.clone().into_os_string()),
+            _ => None,
+        }));
+        let home_trash = trash.home_trash().unwrap();
+        fs::create_dir_all(&test_dir).unwrap();
+
+        let victim = test_dir.join("my file.txt");
+        fs::File::create(&victim).unwrap();
+        assert_eq!(
+            trash.trash_file(&victim).unwrap(),
+            home_trash.join("files/my file.txt")
+        );
+        assert!(!victim.exists());
+        let info = fs::read_to_string(home_trash.join("info/my file.txt.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next(),
+            Some(&*format!("Path={}", escape_path(victim.as_os_str())))
+        );
+        assert!(lines.next().unwrap().starts_with("DeletionDate="));
+
+        fs::File::create(&victim).unwrap();
+        assert_eq!(
+            trash.trash_file(&victim).unwrap(),
+            home_trash.join("files/my file.txt.2")
+        );
+        assert!(home_trash.join("info/my file.txt.2.trashinfo").exists());
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_trash_file_symlinks() {
+        let test_dir = env::current_dir()
+            .unwrap()
+            .join("test_files/trash-symlinks");
+        let data_home = test_dir.join("data");
+        let trash = Trash::new(&BaseDirectories::with_env("", "", &|name| match name {
+            "XDG_DATA_HOME" => Some(data_home.clone().into_os_string()),
+            _ => None,
+        }));
+        let home_trash = trash.home_trash().unwrap();
+        fs::create_dir_all(test_dir.join("real")).unwrap();
+        fs::create_dir_all(home_trash.join("files")).unwrap();
+        std::os::unix::fs::symlink(test_dir.join("real"), test_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(test_dir.join("missing"), home_trash.join("files/note.txt"))
+            .unwrap();
+
+        fs::File::create(test_dir.join("real/note.txt")).unwrap();
+        // The dangling symlink already in the trash is not overwritten.
+        assert_eq!(
+            trash.trash_file(test_dir.join("link/note.txt")).unwrap(),
+            home_trash.join("files/note.txt.2")
+        );
+        assert!(fs::symlink_metadata(home_trash.join("files/note.txt")).is_ok());
+        let info = fs::read_to_string(home_trash.join("info/note.txt.2.trashinfo")).unwrap();
+        let original = fs::canonicalize(&test_dir).unwrap().join("real/note.txt");
+        assert!(info.contains(&format!("Path={}\n", escape_path(original.as_os_str()))));
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_info_format() {
+        assert_eq!(escape_path(OsStr::new("/a b/%/ü")), "/a%20b/%25/%C3%BC");
+        assert_eq!(
+            format_deletion_date(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "2000-02-29T00:00:00"
+        );
+        assert_eq!(
+            format_deletion_date(UNIX_EPOCH + Duration::from_secs(1700000000)),
+            "2023-11-14T22:13:20"
+        );
+    }
+}